@@ -2,7 +2,7 @@
 //!
 //! Based on the links preprocessor in the main mdBook project.
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use clap::{App, Arg, SubCommand};
 use log::{error, warn};
 use mdbook::utils::take_anchored_lines;
@@ -13,17 +13,33 @@ use mdbook::{
     preprocess::{CmdPreprocessor, Preprocessor, PreprocessorContext},
 };
 use once_cell::sync::Lazy;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use pulldown_cmark_to_cmark::cmark;
 use regex::{CaptureMatches, Captures, Regex};
 use std::{
+    collections::HashMap,
+    convert::TryFrom,
     fs, io,
     ops::{Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeTo},
     path::{Path, PathBuf},
     process,
 };
+use url::Url;
 
 const ESCAPE_CHAR: char = '\\';
 const MAX_LINK_NESTED_DEPTH: usize = 10;
 
+/// Enables the GFM extensions that mdBook's own renderer (`mdbook::utils::new_cmark_parser`)
+/// also enables, so parsing and re-serializing included content doesn't corrupt GFM tables,
+/// footnotes, strikethrough, or task lists. Heading attributes and smart punctuation are not
+/// enabled.
+fn cmark_parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
 fn main() -> Result<(), Error> {
     env_logger::init();
     let app = App::new(MdInclude::NAME)
@@ -124,10 +140,10 @@ where
 
     for link in find_links(s) {
         replaced.push_str(&s[previous_end_index..link.start_index]);
-        match link.render_with_path(path) {
+        match link.render_with_path(path, source, depth) {
             Ok(mut new_content) => {
                 if let Some(relative_path) = link.link_type.clone().relative_path(path) {
-                    new_content = update_relative_links(&new_content, &path, &relative_path);
+                    new_content = update_relative_links(&new_content, path, &relative_path);
                 }
                 if depth < MAX_LINK_NESTED_DEPTH {
                     if let Some(rel_path) = link.link_type.relative_path(path) {
@@ -163,57 +179,275 @@ where
 /// This function updates relative links in `content` based on the provided `relative_path`
 /// and `path`. For example, if you use `{{#mdinclude ./my_folder/README.md}}`, then links
 /// in `README.md` will be updated with `my_folder`.
+///
+/// This walks the `pulldown-cmark` event stream rather than matching against the raw text, so
+/// links and images inside code spans/blocks are left alone, and only destinations that are
+/// actually filesystem-relative (not an absolute URL, a `#fragment`, a `/root-absolute` path, or
+/// a `//protocol-relative` host) get rewritten.
 fn update_relative_links(content: &str, path: &Path, relative_path: &Path) -> String {
     // Strip the `path` prefix from `relative_path` to get the relative folder
     let Ok(relative_folder) = relative_path.strip_prefix(path) else {
         return content.to_owned();
     };
 
-    // Regex to match Markdown image and link syntax
-    let re = Regex::new(
-        r#"(?x)
-        !\[(.*?)\]\((./[^)]+)\)|           # Markdown image ![alt text](path)
-        \[(.*?)\]\((./[^)]+)\)           # Markdown link [text](path)
-        "#,
-    )
-    .unwrap();
-
-    // Replace all matches using the regex
-    let updated_content = re.replace_all(content, |caps: &regex::Captures| {
-        // Extract the relative link
-        let relative_link = if let Some(link) = caps.get(2) {
-            link.as_str()
-        } else {
-            caps.get(4).map_or("", |m| m.as_str())
-        };
+    // Reference-style usages (`[text][id]`) are resolved by pulldown-cmark at parse time against
+    // their `[id]: ./path` definition, so the `Tag::Link`/`Tag::Image` event we see already
+    // carries the definition's original target. Rewriting that event is enough to cover both
+    // forms; `cmark()` serializes the link from that already rewritten event rather than from the
+    // original definition line, so the definition must not be rewritten separately, or its target
+    // would be prefixed twice.
+    let events = Parser::new_ext(content, cmark_parser_options())
+        .map(|event| rewrite_link_event(event, relative_folder));
+
+    let mut buf = String::new();
+    cmark(events, &mut buf).expect("formatting Markdown events cannot fail");
+    buf
+}
+
+/// Whether `target` is a link/image destination that should be rewritten: not an absolute URL
+/// (any scheme, including `mailto:`), not fragment-only (`#foo`), not root-absolute (`/foo`),
+/// and not protocol-relative (`//host/foo`).
+fn is_relative_target(target: &str) -> bool {
+    !target.is_empty()
+        && !target.starts_with('#')
+        && !target.starts_with("//")
+        && !target.starts_with('/')
+        && Url::parse(target).is_err()
+}
 
-        // Create a PathBuf from the relative_folder and the relative link
-        let mut new_path = PathBuf::from(relative_folder);
-        new_path.push(Path::new(relative_link));
+fn rewrite_relative_target(target: &str, relative_folder: &Path) -> String {
+    if !is_relative_target(target) {
+        return target.to_owned();
+    }
 
-        // Normalize the path to remove redundant components (like `./`)
-        let updated_link = new_path.display().to_string().replace("\\", "/"); // Ensure Unix-style path separators
+    let mut new_path = relative_folder.to_path_buf();
+    new_path.push(target);
+    new_path.display().to_string().replace('\\', "/")
+}
 
-        // Determine the replacement based on the match
-        if let Some(alt_text) = caps.get(1) {
-            // Handle Markdown image with alt text
-            format!("![{}]({})", alt_text.as_str(), updated_link)
-        } else if let Some(text) = caps.get(3) {
-            // Handle Markdown link
-            format!("[{}]({})", text.as_str(), updated_link)
+fn rewrite_link_event<'a>(event: Event<'a>, relative_folder: &Path) -> Event<'a> {
+    match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: rewrite_relative_target(&dest_url, relative_folder).into(),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: rewrite_relative_target(&dest_url, relative_folder).into(),
+            title,
+            id,
+        }),
+        Event::Html(html) => Event::Html(rewrite_html_attrs(&html, relative_folder).into()),
+        Event::InlineHtml(html) => {
+            Event::InlineHtml(rewrite_html_attrs(&html, relative_folder).into())
+        }
+        event => event,
+    }
+}
+
+/// Rewrites `src="..."`/`href="..."` attributes (single- or double-quoted) found in a raw HTML
+/// event.
+fn rewrite_html_attrs(html: &str, relative_folder: &Path) -> String {
+    static ATTR_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"(?i)\b(src|href)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+    ATTR_RE
+        .replace_all(html, |caps: &Captures| {
+            let (quote, value) = match (caps.get(2), caps.get(3)) {
+                (Some(v), _) => ('"', v.as_str()),
+                (_, Some(v)) => ('\'', v.as_str()),
+                _ => unreachable!("regex always captures one of the two quote alternatives"),
+            };
+            format!(
+                "{}={quote}{}{quote}",
+                &caps[1],
+                rewrite_relative_target(value, relative_folder)
+            )
+        })
+        .into_owned()
+}
+
+/// Whether `path` contains any glob metacharacters (`*` or `?`), in which case it should be
+/// expanded against the filesystem rather than read directly.
+fn contains_glob_metachars(path: &str) -> bool {
+    path.contains('*') || path.contains('?')
+}
+
+/// Translates a glob pattern (`**/` for any number of directories, `*` for any run of
+/// non-separator characters, `?` for a single non-separator character) into an anchored regex
+/// matching `/`-separated relative paths.
+fn glob_to_regex(pattern: &str) -> Regex {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::from("^");
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i..].starts_with(&['*', '*', '/']) {
+            out.push_str("(?:.*/)?");
+            i += 3;
+        } else if chars[i..].starts_with(&['*', '*']) {
+            out.push_str(".*");
+            i += 2;
+        } else if chars[i] == '*' {
+            out.push_str("[^/]*");
+            i += 1;
+        } else if chars[i] == '?' {
+            out.push_str("[^/]");
+            i += 1;
         } else {
-            // In case something unexpected happens, just return the original match
-            caps.get(0).unwrap().as_str().to_string()
+            match chars[i] {
+                '.' | '+' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' | '\\' => {
+                    out.push('\\');
+                    out.push(chars[i]);
+                }
+                c => out.push(c),
+            }
+            i += 1;
         }
-    });
+    }
+
+    out.push('$');
+    Regex::new(&out).expect("translated glob pattern is always a valid regex")
+}
+
+/// Expands a glob include `pattern` (relative to `base`) by walking `base` and collecting every
+/// file whose path (relative to `base`) matches, in sorted order.
+fn expand_glob(base: &Path, pattern: &Path) -> Vec<PathBuf> {
+    let pattern_str = pattern.to_string_lossy().replace('\\', "/");
+    let components: Vec<&str> = pattern_str.split('/').collect();
+    let glob_start = components
+        .iter()
+        .position(|c| contains_glob_metachars(c))
+        .unwrap_or(components.len());
+
+    let search_base = components[..glob_start]
+        .iter()
+        .fold(base.to_path_buf(), |dir, c| dir.join(c));
+    let re = glob_to_regex(&components[glob_start..].join("/"));
+
+    let mut matches = Vec::new();
+    walk_glob_matches(&search_base, &search_base, &re, &mut matches);
+    matches.sort();
+    matches
+}
 
-    updated_content.into_owned()
+fn walk_glob_matches(base: &Path, dir: &Path, re: &Regex, matches: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_glob_matches(base, &path, re, matches);
+        } else if let Ok(relative) = path.strip_prefix(base) {
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if re.is_match(&relative) {
+                matches.push(path);
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 enum LinkType {
     Escaped,
-    Include(PathBuf, RangeOrAnchor),
+    Include(PathBuf, RangeOrAnchor, IncludeProps),
+    /// `{{#mdinclude_rustdoc ./foo.rs:3:8}}`: like `Include`, but wraps the result in a ```rust
+    /// fence and hides every non-selected, non-blank line behind `# ` instead of dropping it, mirroring
+    /// upstream mdBook's `rustdoc_include`.
+    IncludeRustdoc(PathBuf, RangeOrAnchor),
+}
+
+/// Extra `key=value` arguments given to an `{{#mdinclude}}` directive, e.g. the `shift` in
+/// `{{#mdinclude ./foo.md shift=2}}` or the `name`/`version` in
+/// `{{#mdinclude ./foo.md name=Alice version=1.2}}`.
+#[derive(PartialEq, Debug, Clone, Default)]
+struct IncludeProps {
+    /// Number of heading levels to demote the included content by. When not set explicitly,
+    /// the nesting depth of the include is used instead, so transcluded chapters nest sensibly.
+    shift: Option<usize>,
+    /// Remaining `key=value` pairs, substituted into `{{key}}` placeholders found in the
+    /// included content.
+    args: HashMap<String, String>,
+}
+
+/// Parses the `key=value key2="value with spaces"` arguments trailing an include's path. Each
+/// pair is `key=value`, where `value` runs up to the start of the next `key=` or the end of the
+/// string, so a quoted or space-separated value is captured whole; surrounding matching quotes
+/// are stripped. The `regex` crate has no look-ahead, so the next key's start is found by
+/// locating every `key=` match and using one's start as the previous value's end, rather than
+/// asserting it inline with `(?=...)`.
+fn parse_include_props(rest: &str) -> IncludeProps {
+    static KEY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)([^\s=]+)=").unwrap());
+
+    let keys: Vec<(usize, &str)> = KEY_RE
+        .captures_iter(rest)
+        .map(|caps| {
+            let key = caps.get(1).unwrap();
+            (key.start(), key.as_str())
+        })
+        .collect();
+
+    let mut props = IncludeProps::default();
+    for (i, &(key_start, key)) in keys.iter().enumerate() {
+        let value_start = key_start + key.len() + 1;
+        let value_end = keys
+            .get(i + 1)
+            .map_or(rest.len(), |&(next_start, _)| next_start);
+        let value = unquote(rest[value_start..value_end].trim());
+
+        if key == "shift" {
+            props.shift = value.parse::<usize>().ok();
+        } else {
+            props.args.insert(key.to_owned(), value.to_owned());
+        }
+    }
+    props
+}
+
+fn unquote(s: &str) -> &str {
+    let bytes = s.as_bytes();
+    let is_quoted = bytes.len() >= 2
+        && ((bytes[0] == b'"' && bytes[bytes.len() - 1] == b'"')
+            || (bytes[0] == b'\'' && bytes[bytes.len() - 1] == b'\''));
+
+    if is_quoted {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+/// Replaces `{{key}}` placeholders in `content` with the corresponding value from `args`.
+/// Placeholders with no matching argument are left verbatim.
+fn substitute_placeholders(content: &str, args: &HashMap<String, String>) -> String {
+    if args.is_empty() {
+        return content.to_owned();
+    }
+
+    static PLACEHOLDER_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\{\{([a-zA-Z0-9_]+)\}\}").unwrap());
+
+    PLACEHOLDER_RE
+        .replace_all(content, |caps: &Captures| {
+            args.get(&caps[1])
+                .cloned()
+                .unwrap_or_else(|| caps[0].to_owned())
+        })
+        .into_owned()
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -281,7 +515,15 @@ impl LinkType {
         let base = base.as_ref();
         match self {
             LinkType::Escaped => None,
-            LinkType::Include(p, _) => Some(return_relative_path(base, &p)),
+            // A glob pattern's last path component (e.g. `*.md`) isn't a real directory, and a
+            // single shared relative path can't describe files scattered across directories by
+            // a `**` match anyway — `render_with_path` already rewrites each matched file's
+            // links and nested includes against its own directory, so skip the generic pass.
+            LinkType::Include(ref p, _, _) if contains_glob_metachars(&p.to_string_lossy()) => None,
+            LinkType::Include(p, _, _) => Some(return_relative_path(base, &p)),
+            // Emits a fenced code block rather than Markdown, so it must not go through
+            // relative-link rewriting or recursive expansion.
+            LinkType::IncludeRustdoc(..) => None,
         }
     }
 }
@@ -323,13 +565,61 @@ fn parse_range_or_anchor(parts: Option<&str>) -> RangeOrAnchor {
     }
 }
 
-fn parse_md_include_path(path: &str) -> LinkType {
+fn parse_path_and_range(path: &str) -> (PathBuf, RangeOrAnchor) {
     let mut parts = path.splitn(2, ':');
 
     let path = parts.next().unwrap().into();
     let range_or_anchor = parse_range_or_anchor(parts.next());
 
-    LinkType::Include(path, range_or_anchor)
+    (path, range_or_anchor)
+}
+
+fn parse_md_include_path(path: &str, props: IncludeProps) -> LinkType {
+    let (path, range_or_anchor) = parse_path_and_range(path);
+    LinkType::Include(path, range_or_anchor, props)
+}
+
+fn parse_md_include_rustdoc_path(path: &str) -> LinkType {
+    let (path, range_or_anchor) = parse_path_and_range(path);
+    LinkType::IncludeRustdoc(path, range_or_anchor)
+}
+
+/// Demote every heading in `content` by `shift` levels (e.g. an `h1` becomes an `h3` for
+/// `shift == 2`), clamping at `h6`. Detection is driven off the `pulldown-cmark` event stream
+/// rather than a line regex, so headings inside fenced code blocks are left untouched.
+fn shift_headings(content: &str, shift: usize) -> String {
+    if shift == 0 {
+        return content.to_owned();
+    }
+
+    let events = Parser::new_ext(content, cmark_parser_options()).map(|event| match event {
+        Event::Start(Tag::Heading {
+            level,
+            id,
+            classes,
+            attrs,
+        }) => Event::Start(Tag::Heading {
+            level: shift_heading_level(level, shift),
+            id,
+            classes,
+            attrs,
+        }),
+        Event::End(TagEnd::Heading(level)) => {
+            Event::End(TagEnd::Heading(shift_heading_level(level, shift)))
+        }
+        event => event,
+    });
+
+    let mut buf = String::new();
+    cmark(events, &mut buf).expect("formatting Markdown events cannot fail");
+    buf
+}
+
+fn shift_heading_level(level: HeadingLevel, shift: usize) -> HeadingLevel {
+    let shifted = (level as usize)
+        .saturating_add(shift)
+        .min(HeadingLevel::H6 as usize);
+    HeadingLevel::try_from(shifted).unwrap_or(HeadingLevel::H6)
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -344,11 +634,19 @@ impl<'a> Link<'a> {
     fn from_capture(cap: Captures<'a>) -> Option<Link<'a>> {
         let link_type = match (cap.get(0), cap.get(1), cap.get(2)) {
             (_, Some(typ), Some(rest)) => {
-                let mut path_props = rest.as_str().split_whitespace();
-                let file_arg = path_props.next();
+                let rest = rest.as_str().trim_start();
+                let (file_arg, props_rest) = match rest.find(char::is_whitespace) {
+                    Some(idx) => (Some(&rest[..idx]), &rest[idx..]),
+                    None if rest.is_empty() => (None, ""),
+                    None => (Some(rest), ""),
+                };
 
                 match (typ.as_str(), file_arg) {
-                    ("mdinclude", Some(pth)) => Some(parse_md_include_path(pth)),
+                    ("mdinclude", Some(pth)) => {
+                        let props = parse_include_props(props_rest);
+                        Some(parse_md_include_path(pth, props))
+                    }
+                    ("mdinclude_rustdoc", Some(pth)) => Some(parse_md_include_rustdoc_path(pth)),
                     _ => None,
                 }
             }
@@ -368,19 +666,75 @@ impl<'a> Link<'a> {
         })
     }
 
-    fn render_with_path<P: AsRef<Path>>(&self, base: P) -> Result<String> {
+    fn render_with_path<P: AsRef<Path>>(
+        &self,
+        base: P,
+        source: &Path,
+        depth: usize,
+    ) -> Result<String> {
         let base = base.as_ref();
         match self.link_type {
             // omit the escape char
             LinkType::Escaped => Ok(self.link_text[1..].to_owned()),
-            LinkType::Include(ref pat, ref range_or_anchor) => {
+            LinkType::Include(ref pat, ref range_or_anchor, ref props) => {
+                if contains_glob_metachars(&pat.to_string_lossy()) {
+                    let matches = expand_glob(base, pat);
+                    if matches.is_empty() {
+                        return Err(anyhow!(
+                            "No files matched glob include {} ({})",
+                            self.link_text,
+                            base.join(pat).display(),
+                        ));
+                    }
+
+                    // Each matched file keeps its own parent directory as the base for
+                    // relative-link rewriting and nested `{{#mdinclude}}` recursion, since a
+                    // `**` pattern can match files spread across several directories — unlike
+                    // the single-file case, there is no one shared relative path to fall back
+                    // on for the whole concatenated result.
+                    let mut rendered = Vec::with_capacity(matches.len());
+                    for target in &matches {
+                        let s = fs::read_to_string(target).with_context(|| {
+                            format!(
+                                "Could not read file for link {} ({})",
+                                self.link_text,
+                                target.display(),
+                            )
+                        })?;
+                        let file_content = render_included_file(&s, range_or_anchor, props, depth);
+                        let file_dir = target.parent().unwrap_or(base);
+                        let file_content = update_relative_links(&file_content, base, file_dir);
+                        let file_content = if depth < MAX_LINK_NESTED_DEPTH {
+                            replace_all(&file_content, file_dir, source, depth + 1)
+                        } else {
+                            error!(
+                                "Stack depth exceeded in {}. Check for cyclic includes",
+                                source.display()
+                            );
+                            file_content
+                        };
+                        rendered.push(file_content);
+                    }
+                    Ok(rendered.join("\n\n"))
+                } else {
+                    let target = base.join(pat);
+
+                    fs::read_to_string(&target)
+                        .map(|s| render_included_file(&s, range_or_anchor, props, depth))
+                        .with_context(|| {
+                            format!(
+                                "Could not read file for link {} ({})",
+                                self.link_text,
+                                target.display(),
+                            )
+                        })
+                }
+            }
+            LinkType::IncludeRustdoc(ref pat, ref range_or_anchor) => {
                 let target = base.join(pat);
 
                 fs::read_to_string(&target)
-                    .map(|s| match range_or_anchor {
-                        RangeOrAnchor::Range(range) => take_lines(&s, range.clone()),
-                        RangeOrAnchor::Anchor(anchor) => take_anchored_lines(&s, anchor),
-                    })
+                    .map(|s| render_rustdoc_include(&s, range_or_anchor))
                     .with_context(|| {
                         format!(
                             "Could not read file for link {} ({})",
@@ -393,6 +747,95 @@ impl<'a> Link<'a> {
     }
 }
 
+/// Wraps `content` in a ```rust fence, keeping only the selected range/anchor verbatim and
+/// hiding every other non-blank line behind a `# ` prefix so it stays invisible in the
+/// rendered book but is still available to rustdoc/the playground.
+fn render_rustdoc_include(content: &str, range_or_anchor: &RangeOrAnchor) -> String {
+    let body = match range_or_anchor {
+        RangeOrAnchor::Range(range) => take_rustdoc_include_lines(content, range),
+        RangeOrAnchor::Anchor(anchor) => take_rustdoc_include_anchored_lines(content, anchor),
+    };
+
+    format!("```rust\n{body}\n```")
+}
+
+fn take_rustdoc_include_lines(content: &str, range: &LineRange) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let last_line = lines.len().saturating_sub(1);
+    let mut output = String::with_capacity(content.len());
+
+    for (line_number, line) in lines.into_iter().enumerate() {
+        if !range.contains(&line_number) && !line.trim().is_empty() {
+            output.push_str("# ");
+        }
+        output.push_str(line);
+        if line_number != last_line {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+fn take_rustdoc_include_anchored_lines(content: &str, anchor: &str) -> String {
+    let start_marker = format!("ANCHOR: {anchor}");
+    let end_marker = format!("ANCHOR_END: {anchor}");
+    let mut within_anchor = false;
+    let mut kept_lines: Vec<&str> = Vec::new();
+    let mut hide: Vec<bool> = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.ends_with(&start_marker) {
+            within_anchor = true;
+            continue;
+        }
+        if trimmed.ends_with(&end_marker) {
+            within_anchor = false;
+            continue;
+        }
+        if trimmed.contains("ANCHOR:") || trimmed.contains("ANCHOR_END:") {
+            // A marker for a different anchor; drop it entirely.
+            continue;
+        }
+
+        hide.push(!within_anchor && !trimmed.is_empty());
+        kept_lines.push(line);
+    }
+
+    let last_line = kept_lines.len().saturating_sub(1);
+    let mut output = String::with_capacity(content.len());
+    for (line_number, line) in kept_lines.into_iter().enumerate() {
+        if hide[line_number] {
+            output.push_str("# ");
+        }
+        output.push_str(line);
+        if line_number != last_line {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Selects the requested range/anchor out of an included file's contents, then applies
+/// placeholder substitution and heading shifting.
+fn render_included_file(
+    content: &str,
+    range_or_anchor: &RangeOrAnchor,
+    props: &IncludeProps,
+    depth: usize,
+) -> String {
+    let selected = match range_or_anchor {
+        RangeOrAnchor::Range(range) => take_lines(content, range.clone()),
+        RangeOrAnchor::Anchor(anchor) => take_anchored_lines(content, anchor),
+    };
+    let substituted = substitute_placeholders(&selected, &props.args);
+
+    // Fall back to the nesting depth so that a chapter included several levels deep still
+    // nests its headings sensibly.
+    let shift = props.shift.unwrap_or(depth);
+    shift_headings(&substituted, shift)
+}
+
 struct LinkIter<'a>(CaptureMatches<'a, 'a>);
 
 impl<'a> Iterator for LinkIter<'a> {
@@ -494,26 +937,192 @@ mod tests {
 
     #[test]
     fn update_relative_links_works() {
-        let inputs_and_outputs = [
+        let inputs_and_expected_targets = [
             (
                 "My image here: ![my image](./.hidden/subfolder/image/image.png), and it is really cool!",
-                "My image here: ![my image](with/subfolder/./.hidden/subfolder/image/image.png), and it is really cool!"
+                "with/subfolder/./.hidden/subfolder/image/image.png",
             ),
             (
                 "My image here: [my link](./.hidden/subfolder/tests/test.rs), and it is really cool!",
-                "My image here: [my link](with/subfolder/./.hidden/subfolder/tests/test.rs), and it is really cool!"
+                "with/subfolder/./.hidden/subfolder/tests/test.rs",
             ),
         ];
         let path = Path::new("/long/concrete/path/to/project/");
         let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
 
-        for (input, output) in inputs_and_outputs.into_iter() {
+        for (input, expected_target) in inputs_and_expected_targets.into_iter() {
             let final_content = update_relative_links(input, path, relative_path);
 
-            assert_eq!(final_content, output)
+            assert!(
+                final_content.contains(expected_target),
+                "expected {final_content:?} to contain {expected_target:?}"
+            );
         }
     }
 
+    #[test]
+    fn update_relative_links_skips_absolute_and_fragment_targets() {
+        let path = Path::new("/long/concrete/path/to/project/");
+        let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
+
+        let content = "[external](https://example.com/foo) [mail](mailto:a@b.com) \
+             [frag](#section) [root](/foo) [proto](//example.com/foo)";
+        let final_content = update_relative_links(content, path, relative_path);
+
+        assert!(final_content.contains("https://example.com/foo"));
+        assert!(final_content.contains("mailto:a@b.com"));
+        assert!(final_content.contains("#section"));
+        assert!(final_content.contains("(/foo)"));
+        assert!(final_content.contains("//example.com/foo"));
+    }
+
+    #[test]
+    fn update_relative_links_rewrites_reference_definitions_exactly_once() {
+        let path = Path::new("/long/concrete/path/to/project/");
+        let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
+
+        let content = "[my link][ref]\n\n[ref]: ./image.png";
+        let final_content = update_relative_links(content, path, relative_path);
+
+        assert!(final_content.contains("with/subfolder/./image.png"));
+        assert!(!final_content.contains("with/subfolder/with/subfolder/"));
+    }
+
+    #[test]
+    fn update_relative_links_leaves_footnote_text_untouched() {
+        let path = Path::new("/long/concrete/path/to/project/");
+        let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
+
+        let content = "Some text.[^1]\n\n[^1]: This is my footnote text.\n";
+        let final_content = update_relative_links(content, path, relative_path);
+
+        assert!(final_content.contains("[^1]: This is my footnote text."));
+        assert!(!final_content.contains("with/subfolder"));
+    }
+
+    #[test]
+    fn update_relative_links_ignores_reference_like_lines_in_code_blocks() {
+        let path = Path::new("/long/concrete/path/to/project/");
+        let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
+
+        let content = "```\n[ref]: ./x.md\n```\n";
+        let final_content = update_relative_links(content, path, relative_path);
+
+        assert!(final_content.contains("[ref]: ./x.md"));
+        assert!(!final_content.contains("with/subfolder"));
+    }
+
+    #[test]
+    fn update_relative_links_rewrites_single_quoted_html_attrs() {
+        let path = Path::new("/long/concrete/path/to/project/");
+        let relative_path = Path::new("/long/concrete/path/to/project/with/subfolder/");
+
+        let content = "<a href='./rel.md'>link</a>";
+        let final_content = update_relative_links(content, path, relative_path);
+
+        assert!(final_content.contains("with/subfolder/./rel.md"));
+    }
+
+    #[test]
+    fn shift_headings_demotes_and_clamps() {
+        let content = "# Title\n\nSome text.\n\n##### Almost Bottom\n";
+        let shifted = shift_headings(content, 2);
+
+        assert!(shifted.contains("### Title"));
+        assert!(shifted.contains("###### Almost Bottom"));
+    }
+
+    #[test]
+    fn shift_headings_ignores_code_blocks() {
+        let content = "# Title\n\n```\n# not a heading\n```\n";
+        let shifted = shift_headings(content, 1);
+
+        assert!(shifted.contains("## Title"));
+        assert!(shifted.contains("# not a heading"));
+        assert!(!shifted.contains("## not a heading"));
+    }
+
+    #[test]
+    fn parse_include_props_reads_quoted_and_bare_values() {
+        let props = parse_include_props(r#"name=Alice version="1.2.0" shift=2"#);
+
+        assert_eq!(props.shift, Some(2));
+        assert_eq!(props.args.get("name").map(String::as_str), Some("Alice"));
+        assert_eq!(props.args.get("version").map(String::as_str), Some("1.2.0"));
+    }
+
+    #[test]
+    fn substitute_placeholders_fills_known_and_skips_unknown() {
+        let mut args = HashMap::new();
+        args.insert("name".to_owned(), "Alice".to_owned());
+
+        let content = "Hello {{name}}, version is {{version}}.";
+        let result = substitute_placeholders(content, &args);
+
+        assert_eq!(result, "Hello Alice, version is {{version}}.");
+    }
+
+    #[test]
+    fn glob_to_regex_translates_wildcards() {
+        let re = glob_to_regex("chapters/*.md");
+
+        assert!(re.is_match("chapters/intro.md"));
+        assert!(!re.is_match("chapters/sub/intro.md"));
+        assert!(!re.is_match("chapters/intro.rs"));
+    }
+
+    #[test]
+    fn glob_to_regex_translates_double_star() {
+        let re = glob_to_regex("chapters/**/*.md");
+
+        assert!(re.is_match("chapters/intro.md"));
+        assert!(re.is_match("chapters/sub/intro.md"));
+        assert!(!re.is_match("chapters/sub/intro.rs"));
+    }
+
+    #[test]
+    fn contains_glob_metachars_detects_wildcards() {
+        assert!(contains_glob_metachars("chapters/*.md"));
+        assert!(contains_glob_metachars("chapters/file?.md"));
+        assert!(!contains_glob_metachars("chapters/intro.md"));
+    }
+
+    #[test]
+    fn take_rustdoc_include_lines_hides_non_selected_lines() {
+        let content = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+        let range = LineRange::from(1..3);
+
+        let result = take_rustdoc_include_lines(content, &range);
+
+        assert!(result.contains("    let x = 1;"));
+        assert!(result.contains("    println!(\"{}\", x);"));
+        assert!(result.contains("# fn main() {"));
+        assert!(result.contains("# }"));
+    }
+
+    #[test]
+    fn take_rustdoc_include_anchored_lines_hides_outside_anchor() {
+        let content =
+            "fn main() {\n    // ANCHOR: example\n    let x = 1;\n    // ANCHOR_END: example\n}\n";
+
+        let result = take_rustdoc_include_anchored_lines(content, "example");
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines, vec!["# fn main() {", "    let x = 1;", "# }"]);
+        assert!(!result.contains("ANCHOR"));
+    }
+
+    #[test]
+    fn render_rustdoc_include_wraps_in_rust_fence() {
+        let content = "fn main() {}\n";
+        let rendered =
+            render_rustdoc_include(content, &RangeOrAnchor::Range(LineRange::from(RangeFull)));
+
+        assert!(rendered.starts_with("```rust\n"));
+        assert!(rendered.ends_with("```"));
+        assert!(rendered.contains("fn main() {}"));
+    }
+
     #[test]
     fn update_relative_links_skips_random_links() {
         let content =
@@ -523,7 +1132,8 @@ mod tests {
 
         let final_content = update_relative_links(content, path, relative_path);
 
-        // Unchanged
-        assert_eq!(final_content, content)
+        // The code span's contents are left untouched, not treated as a link destination.
+        assert!(final_content.contains("./.hidden/subfolder/image/image.png"));
+        assert!(!final_content.contains("with/subfolder"));
     }
 }